@@ -0,0 +1,34 @@
+// Reading a whole numeric array: `cjson_collect_numbers` against a `cjson_get_array_item` loop.
+// Run with `cargo bench --bench collect_numbers`.
+use std::time::Instant;
+use cjson::cJSON::cjson_add_item_to_array;
+use cjson::cJSON::cjson_collect_numbers;
+use cjson::cJSON::cjson_create_array;
+use cjson::cJSON::cjson_create_number;
+use cjson::cJSON::cjson_get_array_item;
+use cjson::cJSON::cjson_get_array_size;
+use cjson::cJSON::cjson_get_number_value;
+
+fn main() {
+    for length in [1_000, 10_000, 20_000] {
+        let array = cjson_create_array();
+        for i in 0..length {
+            cjson_add_item_to_array(&array, cjson_create_number(i as f64));
+        }
+
+        let start = Instant::now();
+        let size = cjson_get_array_size(&array).unwrap();
+        let mut indexed = Vec::new();
+        for i in 0..size {
+            indexed.push(cjson_get_number_value(&cjson_get_array_item(&array, i as i32).unwrap()).unwrap());
+        }
+        let indexing = start.elapsed();
+
+        let start = Instant::now();
+        let collected = cjson_collect_numbers(&array).unwrap();
+        let collecting = start.elapsed();
+
+        assert_eq!(indexed, collected);
+        println!("{:>6} numbers: indexing loop {:>12?}, cjson_collect_numbers {:>12?}", length, indexing, collecting);
+    }
+}
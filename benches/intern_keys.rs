@@ -0,0 +1,50 @@
+// Heap held by a parsed array of records, with and without `intern_keys`. A counting global
+// allocator measures the live bytes, since `cjson_deep_size_bytes` counts shared keys once per
+// member. Run with `cargo bench --bench intern_keys`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use cjson::cJSON::cjson_get_array_size;
+use cjson::cJSON::cjson_parse_with_options;
+use cjson::cJSON::ParseOptions;
+
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The shape of the `Record` array in tests/inputs, repeated
+fn records(count: usize) -> String {
+    let record = r#"{"precision": "zip", "Latitude": 37.7668, "Longitude": -122.3959, "Address": "",
+        "City": "SAN FRANCISCO", "State": "CA", "Zip": "94107", "Country": "US"}"#;
+    format!("[{}]", vec![record; count].join(","))
+}
+
+fn main() {
+    let input = records(20_000);
+    for intern_keys in [false, true] {
+        let options = ParseOptions { intern_keys, ..Default::default() };
+        let before = LIVE_BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let root = cjson_parse_with_options(&input, &options).unwrap();
+        let elapsed = start.elapsed();
+        let held = LIVE_BYTES.load(Ordering::Relaxed) - before;
+
+        assert_eq!(cjson_get_array_size(&root), Some(20_000));
+        println!("intern_keys: {:<5} {:>10} bytes held, parsed in {:?}", intern_keys, held, elapsed);
+    }
+}
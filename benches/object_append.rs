@@ -0,0 +1,22 @@
+// Building a large object one member at a time; with the tail pointer the time per key
+// should stay flat as the object grows. Run with `cargo bench --bench object_append`.
+use std::time::Instant;
+use cjson::cJSON::cjson_add_number_to_object;
+use cjson::cJSON::cjson_create_object;
+use cjson::cJSON::cjson_get_object_size;
+
+fn main() {
+    for keys in [5_000, 50_000] {
+        let names: Vec<String> = (0..keys).map(|i| format!("key{}", i)).collect();
+
+        let start = Instant::now();
+        let object = cjson_create_object();
+        for (i, name) in names.iter().enumerate() {
+            cjson_add_number_to_object(&object, name, i as f64);
+        }
+        let elapsed = start.elapsed();
+
+        assert_eq!(cjson_get_object_size(&object), Some(keys));
+        println!("{:>6} keys: {:>12?} ({:?} per key)", keys, elapsed, elapsed / keys as u32);
+    }
+}
@@ -16,6 +16,8 @@ use cjson::cJSON::cjson_create_int_array;
 use cjson::cJSON::cjson_add_item_to_array;
 use cjson::cJSON::cjson_version;
 use cjson::cJSON::cjson_print_preallocated;
+use cjson::cJSON::cjson_printed_size;
+use cjson::cJSON::cjson_add_object_to_object;
 
 
 
@@ -36,11 +38,11 @@ fn print_preallocated(root: &Rc<RefCell<CJSON>>) -> Result<(), String> {
     let out = cjson_print(root).ok_or("Failed to generate JSON string")?;
 
     // Create a buffer to succeed (with extra space for safety)
-    let len = out.len() + 5;
+    let len = cjson_printed_size(root, true) + 5;
     let mut buf = String::with_capacity(len);
 
     // Create a buffer with exact size (to simulate potential failure)
-    let len_fail = out.len();
+    let len_fail = cjson_printed_size(root, true);
     let mut buf_fail = String::with_capacity(len_fail);
 
     // Attempt to print into the buffer with extra capacity
@@ -112,8 +114,7 @@ fn create_objects() {
     // Create a "Video" JSON object
     let root = cjson_create_object();
     cjson_add_item_to_object(&root, "name", cjson_create_string("Jack (\"Bee\") Nimble"));
-    let fmt = cjson_create_object();
-    cjson_add_item_to_object(&root, "format", Rc::clone(&fmt));
+    let fmt = cjson_add_object_to_object(&root, "format").expect("Object Should Not Be NULL");
     cjson_add_string_to_object(&fmt, "type", "rect");
     cjson_add_number_to_object(&fmt, "width", 1920.0);
     cjson_add_number_to_object(&fmt, "height", 1080.0);
@@ -150,14 +151,12 @@ fn create_objects() {
 
     // Create a "Gallery" JSON object
     let root = cjson_create_object();
-    let img = cjson_create_object();
-    cjson_add_item_to_object(&root, "Image", Rc::clone(&img));
+    let img = cjson_add_object_to_object(&root, "Image").expect("Object Should Not Be NULL");
     cjson_add_number_to_object(&img, "Width", 800.0);
     cjson_add_number_to_object(&img, "Height", 600.0);
     cjson_add_string_to_object(&img, "Title", "View from 15th Floor");
 
-    let thm = cjson_create_object();
-    cjson_add_item_to_object(&img, "Thumbnail", Rc::clone(&thm));
+    let thm = cjson_add_object_to_object(&img, "Thumbnail").expect("Object Should Not Be NULL");
     cjson_add_string_to_object(&thm, "Url", "http://www.example.com/image/481989943");
     cjson_add_number_to_object(&thm, "Height", 125.0);
     cjson_add_string_to_object(&thm, "Width", "100");
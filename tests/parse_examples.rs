@@ -4,8 +4,10 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::path::Path;
 use cjson::cJSON::CJSON;
-use cjson::cJSON::cjson_print;
+use cjson::cJSON::cjson_print_buffered;
+use cjson::cJSON::cjson_compare;
 use cjson::cJSON::cjson_parse;
+use cjson::cJSON::cjson_parse_file;
 use cjson::cJSON::cjson_get_error_ptr;
 use cjson::cJSON::cjson_parse_with_length;
 use cjson::cJSON::cjson_delete;
@@ -18,12 +20,9 @@ fn get_test_file_path(filename: &str) -> PathBuf {
 }
 
 fn do_test(test_name: &str) -> Result<(), String> {
-    // Define the base directory for the test files
-    const TEST_DIR_PATH: &str = "tests/inputs/";
-
     // Construct the paths for the test input and expected output files
-    let test_path = format!("{}{}", TEST_DIR_PATH, test_name);
-    let expected_path = format!("{}{}", TEST_DIR_PATH, test_name);
+    let test_path = get_test_file_path(test_name);
+    let expected_path = get_test_file_path(&format!("{}.expected", test_name));
 
     println!("Looking for expected file at: {:?}", expected_path);
 
@@ -34,52 +33,45 @@ fn do_test(test_name: &str) -> Result<(), String> {
     // Read and parse the test input
     let tree = parse_file(&test_path).ok_or("Failed to read or parse test input")?;
 
-    // Print the parsed tree back to JSON
-    let actual = cjson_print(&tree).ok_or("Failed to print tree back to JSON")?;
-
-    //println!("expected {:?}", expected.trim());
-    //println!("actual {:?}", actual.trim());
-
-    let expected_parse = cjson_parse(&expected);
-    if let Some(expected_parse) = &expected_parse {
-        // Compare the actual output with the expected output
-        let expected_output: String =
-            cjson_print(&expected_parse).ok_or("Failed to print expected_parse back to JSON")?;
-        if expected_output.trim() == actual.trim() {
-            println!("Test '{}' passed!", test_name);
-            Ok(())
-        } else {
-            Err(format!(
-                "Test '{}' failed: Output does not match expected",
-                test_name
-            ))
-        }
-    } else {
-        Err(format!(
+    // Compare parsed trees rather than text so the printer's spacing does not matter
+    let expected_tree = cjson_parse(&expected)
+        .ok_or_else(|| format!("Test '{}' failed: expected output is not valid JSON", test_name))?;
+    if !cjson_compare(&tree, &expected_tree, true) {
+        return Err(format!(
             "Test '{}' failed: Output does not match expected",
             test_name
-        ))
+        ));
+    }
+
+    // Printing, in either layout, and parsing again must give back the same tree
+    for format in [false, true] {
+        let printed = cjson_print_buffered(&tree, 256, format).ok_or("Failed to print tree back to JSON")?;
+        let reparsed = cjson_parse(&printed)
+            .ok_or_else(|| format!("Test '{}' failed: printed output does not parse", test_name))?;
+        if !cjson_compare(&tree, &reparsed, true) {
+            return Err(format!(
+                "Test '{}' failed: printed output does not round-trip (format: {})",
+                test_name, format
+            ));
+        }
     }
+
+    println!("Test '{}' passed!", test_name);
+    Ok(())
 }
 
 
 // Helper function to read the file's content
-fn read_file(filename: &str) -> io::Result<String> {
+fn read_file(filename: &Path) -> io::Result<String> {
     let mut file = fs::File::open(filename)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
     Ok(content)
 }
 
-// Helper function to parse a file (assumes `parse_file` function is implemented)
-pub fn parse_file(filename: &str) -> Option<Rc<RefCell<CJSON>>> {
-    // Read the content of the file
-    let content = read_file(filename).ok()?;
-
-    // Parse the JSON content
-    let parsed = cjson_parse(&content);
-
-    parsed
+// Helper function to parse a file
+pub fn parse_file(filename: &Path) -> Option<Rc<RefCell<CJSON>>> {
+    cjson_parse_file(filename).ok()
 }
 
 #[cfg(test)]
@@ -115,7 +107,7 @@ mod tests {
     #[test]
     fn file_test6_should_not_be_parsed() {
       // Read the content of "test6"
-      let test6 = read_file("tests/inputs/test6").expect("Failed to read test6 data");
+      let test6 = read_file(&get_test_file_path("test6")).expect("Failed to read test6 data");
 
       // Attempt to parse the content
       let tree = cjson_parse(&test6);
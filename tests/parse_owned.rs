@@ -0,0 +1,51 @@
+// `cjson_parse_owned` takes over the caller's buffer. The allocations made while parsing are
+// counted, so this test lives in its own binary with a counting global allocator.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use cjson::cJSON::cjson_get_array_size;
+use cjson::cJSON::cjson_parse_bytes;
+use cjson::cJSON::cjson_parse_owned;
+
+struct CountingAllocator;
+
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Bytes allocated while `parse` runs
+fn allocated_by(parse: impl FnOnce()) -> usize {
+    let before = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    parse();
+    ALLOCATED_BYTES.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn test_parse_owned_does_not_copy_input() {
+    // Mostly whitespace, so the tree itself is small next to the input
+    let input = format!("[1, 2]{}", " ".repeat(1 << 20)).into_bytes();
+    let length = input.len();
+
+    // The borrowing parser copies the input into its buffer...
+    let copied = allocated_by(|| {
+        assert_eq!(cjson_parse_bytes(&input).and_then(|root| cjson_get_array_size(&root)), Some(2));
+    });
+    assert!(copied >= length);
+
+    // ...while the owning one parses the caller's vector where it is
+    let owned = allocated_by(|| {
+        assert_eq!(cjson_parse_owned(input).and_then(|root| cjson_get_array_size(&root)), Some(2));
+    });
+    assert!(owned < length / 100, "{} bytes allocated", owned);
+}